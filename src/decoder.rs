@@ -0,0 +1,128 @@
+//! Frame-assembly decoder.
+//!
+//! Turns pattern-index hits from `input::process` into decoded bytes instead of per-symbol log
+//! lines: pattern 0 (SYNC) resets the bit accumulator and enters `Receiving`; subsequent hits
+//! of the high-bit/low-bit patterns push a `1`/`0`, packed MSB-first. A frame is finalized once
+//! `target_bits` bits have been collected, or the gap between edges exceeds every known
+//! pattern (see `on_gap_timeout`), and is handed to the main loop through `take_frame`.
+
+use heapless::Vec;
+
+/// Index into `Settings::current_patterns` that the decoder treats as SYNC.
+pub const SYNC_PATTERN: usize = 0;
+/// Index treated as a `1` bit.
+pub const HIGH_BIT_PATTERN: usize = 1;
+/// Index treated as a `0` bit.
+pub const LOW_BIT_PATTERN: usize = 2;
+
+pub const MAX_FRAME_BYTES: usize = 8;
+const MAX_FRAME_BITS: usize = MAX_FRAME_BYTES * 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Idle,
+    Receiving,
+}
+
+pub struct Decoder {
+    state: State,
+    bits: Vec<u8, MAX_FRAME_BITS>,
+    /// Number of bits to collect before a frame is considered complete.
+    target_bits: usize,
+    frame: Option<([u8; MAX_FRAME_BYTES], usize)>,
+}
+
+impl Decoder {
+    pub const fn new(target_bits: usize) -> Self {
+        Self {
+            state: State::Idle,
+            bits: Vec::new(),
+            target_bits,
+            frame: None,
+        }
+    }
+
+    /// Feed one matched pattern index, as seen by `input::process`, into the state machine.
+    pub fn on_pattern_hit(&mut self, pattern_index: usize) {
+        match pattern_index {
+            SYNC_PATTERN => {
+                self.bits.clear();
+                self.state = State::Receiving;
+            }
+            HIGH_BIT_PATTERN if self.state == State::Receiving => self.push_bit(1),
+            LOW_BIT_PATTERN if self.state == State::Receiving => self.push_bit(0),
+            _ => {}
+        }
+    }
+
+    /// Finalize any in-progress frame with the bits collected so far. Call this once an
+    /// inter-edge gap far larger than any known pattern period has been observed, so a frame
+    /// shorter than `target_bits` doesn't leave the decoder wedged in `Receiving`.
+    pub fn on_gap_timeout(&mut self) {
+        if self.state == State::Receiving && !self.bits.is_empty() {
+            defmt::info!(
+                "Frame finalized by inter-edge gap timeout after {} bits",
+                self.bits.len()
+            );
+            self.finalize();
+            return;
+        }
+        self.state = State::Idle;
+        self.bits.clear();
+    }
+
+    fn push_bit(&mut self, bit: u8) {
+        if self.bits.push(bit).is_err() {
+            defmt::error!("Decoder bit buffer full, dropping frame");
+            self.state = State::Idle;
+            self.bits.clear();
+            return;
+        }
+
+        if self.bits.len() == self.target_bits {
+            self.finalize();
+        }
+    }
+
+    fn finalize(&mut self) {
+        let mut bytes = [0u8; MAX_FRAME_BYTES];
+        let mut byte_count = 0;
+
+        for (i, chunk) in self.bits.chunks(8).enumerate() {
+            let mut byte = 0u8;
+            for (bit_index, &bit) in chunk.iter().enumerate() {
+                byte |= bit << (7 - bit_index);
+            }
+            bytes[i] = byte;
+            byte_count = i + 1;
+        }
+
+        defmt::info!("Decoded frame: {:02x}", &bytes[..byte_count]);
+
+        if checksum_ok(&bytes[..byte_count]) {
+            self.frame = Some((bytes, byte_count));
+        } else {
+            defmt::warn!("Decoded frame failed checksum, dropping");
+        }
+
+        self.state = State::Idle;
+        self.bits.clear();
+    }
+
+    /// Drain the most recently finalized frame, if any, for the caller to consume.
+    pub fn take_frame(&mut self) -> Option<([u8; MAX_FRAME_BYTES], usize)> {
+        self.frame.take()
+    }
+}
+
+/// XOR checksum over all but the last byte, validated against the last byte. Good enough to
+/// catch a garbled bit count; swap for a real CRC once the wire format is pinned down.
+fn checksum_ok(bytes: &[u8]) -> bool {
+    if bytes.len() < 2 {
+        return true;
+    }
+
+    let (payload, checksum) = bytes.split_at(bytes.len() - 1);
+    let computed = payload.iter().fold(0u8, |acc, b| acc ^ b);
+    computed == checksum[0]
+}
@@ -0,0 +1,183 @@
+//! Flash-backed record/replay of captured signal windows.
+//!
+//! Misdecodes are hard to reproduce from log lines alone, so this lets a captured
+//! `SignalWindow` be persisted to on-chip flash and replayed back into the decoder later. Each
+//! snapshot is framed as `[len][samples...][crc32]`, all little-endian, so a page that got cut
+//! off mid-write (power loss, a reset during the write) is rejected on load instead of silently
+//! replaying garbage.
+
+use core::convert::TryInto;
+
+use super::signalbuffer::SignalWindow;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlashError {
+    /// `buf` isn't large enough to hold the frame being written, or the stored window would
+    /// overflow the `SignalWindow` being loaded into.
+    BufferTooSmall,
+    /// `buf` is shorter than the length header claims, e.g. a half-written flash page.
+    Truncated,
+    /// The payload's CRC doesn't match the trailing CRC in the frame.
+    ChecksumMismatch,
+}
+
+/// CRC-32/ISO-HDLC (the same polynomial as zlib/Ethernet), computed bit by bit rather than via a
+/// 256-entry table since frames here are small and flash space is precious.
+fn crc32(bytes: &[u8]) -> u32 {
+    const POLY: u32 = 0xEDB8_8320;
+
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (POLY & mask);
+        }
+    }
+    !crc
+}
+
+/// Serialize `window` into `buf` as `[len][samples...][crc32]` and return the number of bytes
+/// written.
+pub fn store<const N: usize>(
+    window: &SignalWindow<N>,
+    buf: &mut [u8],
+) -> Result<usize, FlashError> {
+    let (first, second) = window.as_slices();
+    let len = first.len() + second.len();
+    let payload_len = 4 + len * 4;
+    let frame_len = payload_len + 4;
+
+    if buf.len() < frame_len {
+        return Err(FlashError::BufferTooSmall);
+    }
+
+    buf[0..4].copy_from_slice(&(len as u32).to_le_bytes());
+
+    let mut offset = 4;
+    for &sample in first.iter().chain(second.iter()) {
+        buf[offset..offset + 4].copy_from_slice(&sample.to_le_bytes());
+        offset += 4;
+    }
+
+    let crc = crc32(&buf[..payload_len]);
+    buf[payload_len..frame_len].copy_from_slice(&crc.to_le_bytes());
+
+    Ok(frame_len)
+}
+
+/// Reconstruct a `SignalWindow` from a frame written by [`store`], rejecting it with
+/// `FlashError::ChecksumMismatch` if the CRC over the payload doesn't match (a corrupt or
+/// half-written flash page) instead of replaying whatever garbage is there.
+pub fn load<const N: usize>(buf: &[u8]) -> Result<SignalWindow<N>, FlashError> {
+    if buf.len() < 8 {
+        return Err(FlashError::Truncated);
+    }
+
+    let len = u32::from_le_bytes(buf[0..4].try_into().unwrap()) as usize;
+
+    // Reject an implausible length before deriving payload_len/frame_len from it: an untrusted
+    // (corrupt or half-written) header could otherwise overflow that arithmetic.
+    if len > N {
+        return Err(FlashError::BufferTooSmall);
+    }
+
+    let payload_len = 4 + len * 4;
+    let frame_len = payload_len + 4;
+
+    if buf.len() < frame_len {
+        return Err(FlashError::Truncated);
+    }
+
+    let stored_crc = u32::from_le_bytes(buf[payload_len..frame_len].try_into().unwrap());
+    if crc32(&buf[..payload_len]) != stored_crc {
+        return Err(FlashError::ChecksumMismatch);
+    }
+
+    let mut window = SignalWindow::<N>::new_const();
+    {
+        let mut producer = window.producer();
+        for chunk in buf[4..payload_len].chunks_exact(4) {
+            let sample = u32::from_le_bytes(chunk.try_into().unwrap());
+            // Can't overrun a window we just created, so only `len > N` (checked above) could
+            // ever fail this.
+            producer.push(sample).map_err(|_| FlashError::BufferTooSmall)?;
+        }
+    }
+
+    Ok(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_then_load_round_trips() {
+        let mut window: SignalWindow<8> = SignalWindow::new_const();
+        window.push(10);
+        window.push(20);
+        window.push(30);
+
+        let mut buf = [0u8; 64];
+        let written = store(&window, &mut buf).unwrap();
+
+        let loaded: SignalWindow<8> = load(&buf[..written]).unwrap();
+        assert_eq!(loaded.get_window().0[..3], [10, 20, 30]);
+    }
+
+    #[test]
+    fn store_rejects_a_buffer_too_small_for_the_frame() {
+        let mut window: SignalWindow<8> = SignalWindow::new_const();
+        window.push(1);
+        window.push(2);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(store(&window, &mut buf), Err(FlashError::BufferTooSmall));
+    }
+
+    #[test]
+    fn load_rejects_a_truncated_frame() {
+        let mut window: SignalWindow<8> = SignalWindow::new_const();
+        window.push(1);
+        window.push(2);
+
+        let mut buf = [0u8; 64];
+        let written = store(&window, &mut buf).unwrap();
+
+        let result: Result<SignalWindow<8>, _> = load(&buf[..written - 1]);
+        assert_eq!(result.unwrap_err(), FlashError::Truncated);
+    }
+
+    #[test]
+    fn load_rejects_a_corrupted_payload() {
+        let mut window: SignalWindow<8> = SignalWindow::new_const();
+        window.push(1);
+        window.push(2);
+
+        let mut buf = [0u8; 64];
+        let written = store(&window, &mut buf).unwrap();
+        buf[4] ^= 0xFF;
+
+        let result: Result<SignalWindow<8>, _> = load(&buf[..written]);
+        assert_eq!(result.unwrap_err(), FlashError::ChecksumMismatch);
+    }
+
+    #[test]
+    fn load_rejects_a_length_header_larger_than_the_target_window() {
+        let mut buf = [0u8; 64];
+        buf[0..4].copy_from_slice(&(9u32).to_le_bytes());
+
+        let result: Result<SignalWindow<8>, _> = load(&buf);
+        assert_eq!(result.unwrap_err(), FlashError::BufferTooSmall);
+    }
+
+    #[test]
+    fn load_rejects_a_length_header_that_would_overflow_frame_len() {
+        let mut buf = [0u8; 64];
+        buf[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let result: Result<SignalWindow<8>, _> = load(&buf);
+        assert_eq!(result.unwrap_err(), FlashError::BufferTooSmall);
+    }
+}
@@ -1,45 +1,96 @@
 mod signalbuffer;
-use crate::patterns::Settings;
+#[cfg(feature = "dma_capture")]
+mod dma;
+#[cfg(feature = "dma_capture")]
+pub use dma::DmaInputCapture;
+mod flash;
+pub use flash::{load as load_signal_window, store as store_signal_window, FlashError};
+mod rssi;
+pub use rssi::RssiGate;
+
+use crate::decoder::Decoder;
+use crate::patterns::{pack_mark_space, Settings};
 
 use {defmt_rtt as _, panic_probe as _};
 
-use cortex_m::interrupt::Mutex;
-
 use signalbuffer::SignalWindow;
 use stm32f0xx_hal::{
-    pac::{interrupt, Interrupt, TIM3},
+    pac::{Interrupt, TIM3},
     time::Hertz,
 };
 
-use core::{
-    cell::{Ref, RefCell},
-    convert::TryInto,
-    panic,
-};
+use core::{convert::TryInto, panic};
+
+/// How TIM3 is wired up, and therefore how captured values must be interpreted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Single channel, any-edge, reset mode: each capture is the raw time since the last edge.
+    Period,
+    /// Dual channel PWM-input: each capture is a `(mark, space)` pair for one full cycle.
+    MarkSpace,
+}
 
-static INPUT_CAPTURE: Mutex<RefCell<Option<InputCapture>>> = Mutex::new(RefCell::new(None));
+/// An inter-edge period at or above this many timer ticks is treated as a gap far longer than
+/// any known pattern, not a real edge — the same upper bound the DMA capture path filters raw
+/// captures against. Used to finalize an in-progress frame instead of leaving the decoder
+/// wedged in `Receiving` forever after a short frame or a dropped edge.
+const GAP_TIMEOUT_TICKS: u32 = 20_000;
 
 pub struct InputCapture {
     tim3: TIM3,
     overflow_counter: u16,
     signal_window: SignalWindow<8>,
+    mode: CaptureMode,
+    rssi_gate: Option<RssiGate>,
+    /// Set by `handle_interrupt` when the last captured period reached `GAP_TIMEOUT_TICKS`;
+    /// consumed by `process` via `take_gap` to finalize whatever frame the decoder was assembling.
+    gap_detected: bool,
 }
 
 // Timer we use input capturing on
 // We wanne use TIM3_CH1 -> bound to PA6 on alternative function 1
+//
+// `InputCapture` is a plain RTIC resource now (no internal singleton): the app puts it behind
+// `#[shared]` so the framework's priority-ceiling locking replaces the old
+// `Mutex<RefCell<Option<InputCapture>>>` bookkeeping.
 impl InputCapture {
-    pub fn init(tim3: TIM3, pclk: Hertz) {
-        unsafe {
-            // really simple gate
-            static mut FIRST: bool = true;
+    pub fn new(tim3: TIM3, pclk: Hertz) -> Self {
+        Self::new_common(tim3, pclk, CaptureMode::Period, |tim3| {
+            // 4. set input to rising and falling edge
+            // 00 -> rising edge, 11 -> any edge
+            tim3.ccer
+                .modify(|_, w| w.cc1p().set_bit().cc1np().set_bit());
+        })
+    }
 
-            if FIRST {
-                FIRST = false;
-            } else {
-                panic!("InputCapture singleton already initialized");
-            }
-        }
+    /// Like [`InputCapture::new`], but configures TIM3 for dual-channel PWM-input capture
+    /// instead of single-channel any-edge capture.
+    ///
+    /// TI1 drives both CH1 and CH2: TI1FP1 is routed to CH1 on the rising edge with the slave
+    /// controller in reset mode, so CCR1 captures the full period. TI1FP2 is routed to CH2 on
+    /// the falling edge, so CCR2 captures the high (mark) time within that same period. Space
+    /// is then simply `period - mark`, letting `handle_interrupt` push separated mark/space
+    /// pairs instead of raw inter-edge periods.
+    pub fn new_pwm(tim3: TIM3, pclk: Hertz) -> Self {
+        Self::new_common(tim3, pclk, CaptureMode::MarkSpace, |tim3| {
+            // CH1 captures TI1FP1 on the rising edge only (it is also the reset trigger)
+            tim3.ccer.modify(|_, w| w.cc1p().clear_bit());
+
+            // CH2 is driven by TI1 as well, but captures TI1FP2 on the falling edge
+            tim3.ccmr1_input().modify(|_, w| w.cc2s().ti1());
+            tim3.ccmr1_input().modify(|_, w| w.ic2f().bits(0b0000));
+            tim3.ccer.modify(|_, w| w.cc2p().set_bit());
+
+            tim3.ccer.modify(|_, w| w.cc2e().set_bit());
+        })
+    }
 
+    fn new_common(
+        tim3: TIM3,
+        pclk: Hertz,
+        mode: CaptureMode,
+        configure_edges: impl FnOnce(&TIM3),
+    ) -> Self {
         // must be disabled for config
         defmt::assert!(tim3.cr1.read().cen().is_disabled());
 
@@ -83,10 +134,8 @@ impl InputCapture {
         let filter: u8 = 0b0000; // sample with 8 samples, normal frequency
         tim3.ccmr1_input().modify(|_, w| w.ic1f().bits(filter));
 
-        // 4. set input to rising and falling edge
-        // 00 -> rising edge, 11 -> any edge
-        tim3.ccer
-            .modify(|_, w| w.cc1p().set_bit().cc1np().set_bit());
+        // 4. set input edge configuration (mode-dependent)
+        configure_edges(&tim3);
 
         // enable reset mode, reset the counter each capture, giving us the time between captures
         tim3.smcr.modify(|_, w| w.sms().reset_mode());
@@ -108,125 +157,148 @@ impl InputCapture {
         }
         cortex_m::peripheral::NVIC::unpend(Interrupt::TIM3);
 
-        cortex_m::interrupt::free(|cs| {
-            defmt::info!("Setting up input capture singleton");
-
-            let prev = INPUT_CAPTURE.borrow(cs).borrow_mut().replace(Self {
-                tim3: tim3,
-                overflow_counter: 0,
-                signal_window: SignalWindow::new_const(),
-            });
-
-            defmt::assert!(prev.is_none());
-
-            defmt::assert!(INPUT_CAPTURE.borrow(cs).borrow_mut().is_some());
-        });
-
         defmt::info!("Timer setup done");
-        // check that it exists
-        InputCapture::input_capture_singleton(|input_capture_ref| {});
+
+        Self {
+            tim3,
+            overflow_counter: 0,
+            signal_window: SignalWindow::new_const(),
+            mode,
+            rssi_gate: None,
+            gap_detected: false,
+        }
     }
 
-    pub fn input_capture_singleton<F, R>(f: F) -> R
-    where
-        F: FnOnce(&mut InputCapture) -> R,
-    {
-        cortex_m::interrupt::free(|cs| {
-            let mut input_capture_ref = INPUT_CAPTURE.borrow(cs).borrow_mut();
+    /// Attach an ADC-backed RSSI squelch: capture is only armed while the sampled level on
+    /// `pin` is at or above `threshold`, which suppresses the noise storm an idle OOK receiver
+    /// otherwise produces. Poll it with [`InputCapture::poll_rssi_gate`].
+    pub fn with_rssi_gate(
+        mut self,
+        adc: stm32f0xx_hal::adc::Adc,
+        pin: stm32f0xx_hal::gpio::gpioa::PA0<stm32f0xx_hal::gpio::Analog>,
+        threshold: u16,
+    ) -> Self {
+        self.disable_input_capture();
+        self.rssi_gate = Some(RssiGate::new(adc, pin, threshold));
+        self
+    }
 
-            if input_capture_ref.is_none() {
-                panic!("InputCapture singleton not initialized");
+    /// Sample the RSSI gate, if one was attached, and arm/disarm capture when the level crosses
+    /// `threshold`. Call this periodically, e.g. from a TIM14 tick.
+    pub fn poll_rssi_gate(&mut self) {
+        let armed = match self.rssi_gate.as_mut() {
+            Some(gate) => gate.poll(),
+            None => return,
+        };
+
+        match armed {
+            Some(true) => {
+                defmt::info!("RSSI above threshold, arming capture");
+                self.enable_input_capture();
             }
-
-            let input_capture_ref = input_capture_ref.as_mut().unwrap();
-
-            f(input_capture_ref)
-        })
+            Some(false) => {
+                defmt::info!("RSSI below threshold, disarming capture");
+                self.disable_input_capture();
+            }
+            None => {}
+        }
     }
 
-    pub fn enable_input_capture() {
-        InputCapture::input_capture_singleton(|input_capture_ref| {
-            input_capture_ref
-                .tim3
-                .ccer
-                .modify(|_, w| w.cc1e().set_bit()); // enable counter
-        });
+    pub fn enable_input_capture(&mut self) {
+        self.tim3.ccer.modify(|_, w| w.cc1e().set_bit()); // enable capture
     }
 
-    pub fn disable_input_capture() {
-        InputCapture::input_capture_singleton(|input_capture_ref| {
-            input_capture_ref
-                .tim3
-                .ccer
-                .modify(|_, w| w.cc1e().clear_bit()); // enable counter
-        });
+    pub fn disable_input_capture(&mut self) {
+        self.tim3.ccer.modify(|_, w| w.cc1e().clear_bit()); // disable capture
     }
 
     /**
      * Handle the interrupt flags and if a capture has happened return the period and reset the overflow counter
      */
-    pub fn handle_interrupt() -> Option<u32> {
-        return InputCapture::input_capture_singleton(|input_capture_ref| {
-            let sr = input_capture_ref.tim3.sr.read();
+    /// In `CaptureMode::MarkSpace` the returned (and pushed) value is the packed `(mark, space)`
+    /// pair from [`pack_mark_space`] instead of the raw period.
+    pub fn handle_interrupt(&mut self) -> Option<u32> {
+        let sr = self.tim3.sr.read();
 
-            if sr.uif().bit_is_set() {
-                input_capture_ref.overflow_counter += 1;
-            }
+        if sr.uif().bit_is_set() {
+            self.overflow_counter += 1;
+        }
 
-            let ret: Option<u32>;
+        let ret: Option<u32>;
 
-            if sr.cc1if().bit_is_set() {
-                let period = input_capture_ref.tim3.ccr1.read().bits() as u32
-                    + ((input_capture_ref.overflow_counter as u32) << 16);
-                input_capture_ref.overflow_counter = 0;
-                ret = Some(period);
-            } else {
-                ret = None;
-            }
+        if sr.cc1if().bit_is_set() {
+            let period =
+                self.tim3.ccr1.read().bits() as u32 + ((self.overflow_counter as u32) << 16);
+            self.overflow_counter = 0;
 
-            input_capture_ref.tim3.sr.reset();
+            if period >= GAP_TIMEOUT_TICKS {
+                self.gap_detected = true;
+            }
 
-            if let Some(value) = ret {
-                if value > 20 {
-                    defmt::info!("Capture value: {}", value);
-                    input_capture_ref.signal_window.push(value);
+            match self.mode {
+                CaptureMode::Period => ret = Some(period),
+                CaptureMode::MarkSpace => {
+                    let mark = self.tim3.ccr2.read().bits() as u32;
+                    let space = period.saturating_sub(mark);
+                    ret = Some(pack_mark_space(mark as u16, space as u16));
                 }
             }
+        } else {
+            ret = None;
+        }
 
-            return ret;
-        });
+        self.tim3.sr.reset();
+
+        if let Some(value) = ret {
+            if value > 20 {
+                defmt::info!("Capture value: {}", value);
+                self.signal_window.push(value);
+            }
+        }
+
+        ret
     }
-}
 
-#[interrupt]
-fn TIM3() {
-    InputCapture::handle_interrupt();
+    /// Read and clear the gap-detected flag set by `handle_interrupt`, so `process` can finalize
+    /// an in-progress frame at most once per gap.
+    fn take_gap(&mut self) -> bool {
+        core::mem::replace(&mut self.gap_detected, false)
+    }
 }
 
-pub fn process(settings: &Settings) {
-    InputCapture::input_capture_singleton(|input_capture_ref| {
-        if input_capture_ref.signal_window.dirty {
-            let (current_window, window_start_index) = input_capture_ref.signal_window.get_window();
-            input_capture_ref.signal_window.dirty = false;
-
-            for (i, pattern) in settings.current_patterns.iter().enumerate() {
-                if pattern.match_window(&current_window) {
-                    if i == 0 {
-                        defmt::info!("\n SYNC bit");
-                    }
-                    defmt::info!(
-                        "Pattern hit! Pattern {} window {}",
-                        pattern.periods,
-                        current_window
-                    );
-
-                    input_capture_ref
-                        .signal_window
-                        .clear_region(window_start_index, pattern.size as usize);
-                }
+/// Check whatever the capture ISR has pushed against the known patterns, reconstructing
+/// decoded frames out of matched windows. Run as the RTIC `process` software task, spawned
+/// whenever `handle_interrupt` flips `signal_window.dirty`. Also finalizes any in-progress
+/// frame if the last inter-edge gap reached `GAP_TIMEOUT_TICKS`, instead of leaving the decoder
+/// wedged waiting for bits that will never arrive.
+pub fn process(settings: &Settings, input_capture: &mut InputCapture, decoder: &mut Decoder) {
+    if input_capture.take_gap() {
+        decoder.on_gap_timeout();
+    }
+
+    if !input_capture.signal_window.is_dirty() {
+        return;
+    }
+
+    let (current_window, window_start_index) = input_capture.signal_window.get_window();
+    input_capture.signal_window.clear_dirty();
+
+    for (i, pattern) in settings.current_patterns.iter().enumerate() {
+        if pattern.match_window(&current_window) {
+            if i == 0 {
+                defmt::info!("\n SYNC bit");
             }
-        } else {
-            return;
+            defmt::info!(
+                "Pattern hit! Pattern {} window {}",
+                pattern.periods,
+                current_window
+            );
+
+            decoder.on_pattern_hit(i);
+
+            input_capture
+                .signal_window
+                .clear_region(window_start_index, pattern.size as usize);
         }
-    });
+    }
 }
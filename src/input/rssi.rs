@@ -0,0 +1,41 @@
+//! Optional RSSI/analog squelch.
+//!
+//! Cheap 433 MHz superheterodyne receivers expose an analog RSSI line. Sampling it lets capture
+//! stay disabled while the receiver is only seeing noise, instead of flooding the capture path
+//! with noise edges and thrashing the `SignalWindow`.
+
+use stm32f0xx_hal::{
+    adc::Adc,
+    gpio::{gpioa::PA0, Analog},
+};
+
+pub struct RssiGate {
+    adc: Adc,
+    pin: PA0<Analog>,
+    threshold: u16,
+    armed: bool,
+}
+
+impl RssiGate {
+    pub fn new(adc: Adc, pin: PA0<Analog>, threshold: u16) -> Self {
+        Self {
+            adc,
+            pin,
+            threshold,
+            armed: false,
+        }
+    }
+
+    /// Sample the RSSI pin and report whether the arm/disarm state flipped since the last poll.
+    pub fn poll(&mut self) -> Option<bool> {
+        let sample: u16 = self.adc.read(&mut self.pin).unwrap_or(0);
+        let above = sample >= self.threshold;
+
+        if above != self.armed {
+            self.armed = above;
+            Some(above)
+        } else {
+            None
+        }
+    }
+}
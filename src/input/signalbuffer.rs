@@ -1,22 +1,152 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+/// Returned by [`Producer::push`] when the ring would lap the tail the consumer hasn't caught
+/// up to yet, instead of silently overwriting a sample that hasn't been read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Overrun;
+
 pub struct SignalWindow<const BUFFER_SIZE: usize> {
     buffer: [u32; BUFFER_SIZE],
-    next_index: u8, // needed although internally known for ring buffer to reconstruct the window
-    pub dirty: bool,
+    // Logical (never-wrapped) write/read positions. `head - tail` is the number of samples the
+    // consumer hasn't caught up to yet; physical index into `buffer` is `position % BUFFER_SIZE`.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dirty: AtomicBool,
+    // Number of valid samples currently in the window, saturating at BUFFER_SIZE. Bounds the
+    // region `as_slices`/`get_window` expose instead of scanning for a `0` sentinel — `0` is a
+    // perfectly legal capture value (a zero-duration or immediately-repeated edge).
+    len: AtomicUsize,
+    // Absolute sample index of the next push, never reset and never wrapped (unlike `head`,
+    // which is only as wide as `usize`). Lets a decoder reference "everything up to sample N"
+    // across buffer wraps and partial clears via `get_from`/`consume_to`.
+    write_count: AtomicU64,
 }
 
 impl<const BUFFER_SIZE: usize> SignalWindow<BUFFER_SIZE> {
     pub const fn new_const() -> Self {
         Self {
             buffer: [0; BUFFER_SIZE],
-            next_index: 0,
-            dirty: false,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dirty: AtomicBool::new(false),
+            len: AtomicUsize::new(0),
+            write_count: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of valid samples currently held in the window (at most `BUFFER_SIZE`).
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Absolute index of the oldest sample still held in the window, in the same domain as
+    /// `get_from`/`consume_to`. Everything before this index has already been overwritten.
+    fn oldest_abs_index(&self) -> u64 {
+        self.write_count
+            .load(Ordering::Acquire)
+            .saturating_sub(self.len() as u64)
+    }
+
+    /// Map an absolute sample index to its current physical slot, returning `None` if that
+    /// index has either not been written yet or has already been overwritten.
+    pub fn get_from(&self, abs_index: u64, count: usize) -> Option<(usize, usize)> {
+        let total_written = self.write_count.load(Ordering::Acquire);
+
+        if abs_index.checked_add(count as u64)? > total_written {
+            return None; // not written yet
+        }
+
+        if abs_index < self.oldest_abs_index() {
+            return None; // already overwritten
         }
+
+        let physical_start = (abs_index % BUFFER_SIZE as u64) as usize;
+        Some((physical_start, count))
+    }
+
+    /// Clear every sample older than `abs_index`, the write-ahead-log style analogue of
+    /// `clear_region` for callers that only track an absolute position.
+    pub fn consume_to(&mut self, abs_index: u64) {
+        let oldest_abs = self.oldest_abs_index();
+
+        if abs_index <= oldest_abs {
+            return;
+        }
+
+        let clear_count = (abs_index - oldest_abs).min(self.len() as u64) as usize;
+        if clear_count == 0 {
+            return;
+        }
+
+        let start = (oldest_abs % BUFFER_SIZE as u64) as usize;
+        self.clear_region(start, clear_count);
+    }
+
+    /// Borrow the producer half of this window: writes samples and advances `head`, `len`,
+    /// `write_count` and `dirty`, and only reads `tail`. It borrows the window mutably, so it
+    /// cannot exist at the same time as a [`Consumer`] of the same window.
+    pub fn producer(&mut self) -> Producer<'_, BUFFER_SIZE> {
+        Producer { window: self }
+    }
+
+    /// Borrow the consumer half of this window: advances `tail` and clears `dirty` (and `len`
+    /// when draining). It borrows the window mutably, so it cannot exist at the same time as a
+    /// [`Producer`] of the same window.
+    pub fn consumer(&mut self) -> Consumer<'_, BUFFER_SIZE> {
+        Consumer { window: self }
     }
 
     pub fn push(&mut self, value: u32) {
-        self.buffer[self.next_index as usize] = value;
-        self.next_index = (self.next_index + 1) % (BUFFER_SIZE as u8);
-        self.dirty = true;
+        // Existing callers predate the overrun signal and don't expect a `Result`; keep
+        // overwriting on lap, same as before `Producer` existed.
+        if self.producer().push(value).is_err() {
+            // drop the oldest unread sample to make room for the new one
+            self.consumer().advance(1);
+            let _ = self.producer().push(value);
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.consumer().clear_dirty();
+    }
+
+    /// Physical index of the oldest sample in the live region — the true start `as_slices` and
+    /// `get_window` both hand out, as opposed to `head % BUFFER_SIZE`, which is only the start
+    /// of the live region once the window is completely full (it's otherwise just "where the
+    /// next write will land").
+    fn region_start(&self) -> usize {
+        let next_write = self.head.load(Ordering::Acquire) % BUFFER_SIZE;
+        let len = self.len();
+        (next_write + BUFFER_SIZE - len) % BUFFER_SIZE
+    }
+
+    /// Return the live region — the `len()` most recent samples, oldest first — as up to two
+    /// borrowed slices: the contiguous run from the oldest sample to the buffer end, and the
+    /// wrapped-around remainder, with no copy. Concatenating the two in order gives the same
+    /// oldest-to-newest sequence `get_window` copies into an owned array.
+    pub fn as_slices(&self) -> (&[u32], &[u32]) {
+        let start = self.region_start();
+        let len = self.len();
+
+        if len == BUFFER_SIZE {
+            if start == 0 {
+                return (&self.buffer[..], &[]);
+            }
+            let (newer, older) = self.buffer.split_at(start);
+            return (older, newer);
+        }
+
+        // not full: the valid region is the last `len` writes, which wrap past the buffer end
+        // once `start + len` exceeds BUFFER_SIZE (e.g. after a lap followed by `clear_region`)
+        if start + len <= BUFFER_SIZE {
+            (&self.buffer[start..start + len], &[])
+        } else {
+            (&self.buffer[start..], &self.buffer[..start + len - BUFFER_SIZE])
+        }
     }
 
     /**
@@ -24,21 +154,12 @@ impl<const BUFFER_SIZE: usize> SignalWindow<BUFFER_SIZE> {
      */
     pub fn get_window(&self) -> ([u32; BUFFER_SIZE], usize) {
         let mut window = [0; BUFFER_SIZE];
+        let window_start = self.region_start();
 
-        // we want the element that was last written to
-        let window_start = self.next_index as usize;
-
-        // copy the next BUFFER_SIZE elements into the window
-        // the modulo operation is needed to wrap around the buffer
+        let (first, second) = self.as_slices();
 
-        for window_index in 0..BUFFER_SIZE {
-            let value_index = (window_start + window_index) % BUFFER_SIZE;
-            let val = self.buffer[value_index];
-            if val == 0 {
-                return (window, window_start);
-            } else {
-                window[window_index] = val;
-            }
+        for (window_index, &val) in first.iter().chain(second.iter()).enumerate() {
+            window[window_index] = val;
         }
 
         (window, window_start)
@@ -53,6 +174,200 @@ impl<const BUFFER_SIZE: usize> SignalWindow<BUFFER_SIZE> {
             self.buffer[index % BUFFER_SIZE] = 0;
         }
 
+        self.len.fetch_update(Ordering::Release, Ordering::Relaxed, |len| {
+            Some(len.saturating_sub(count))
+        })
+        .ok();
+
+        self.consumer().advance(count);
+
         defmt::info!("buffer: {}", self.buffer);
     }
 }
+
+pub struct Producer<'a, const BUFFER_SIZE: usize> {
+    window: &'a mut SignalWindow<BUFFER_SIZE>,
+}
+
+impl<'a, const BUFFER_SIZE: usize> Producer<'a, BUFFER_SIZE> {
+    pub fn push(&mut self, value: u32) -> Result<(), Overrun> {
+        let head = self.window.head.load(Ordering::Relaxed);
+        let tail = self.window.tail.load(Ordering::Acquire);
+
+        if head.wrapping_sub(tail) >= BUFFER_SIZE {
+            return Err(Overrun);
+        }
+
+        self.window.buffer[head % BUFFER_SIZE] = value;
+        self.window.head.store(head.wrapping_add(1), Ordering::Release);
+        self.window
+            .len
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |len| {
+                Some((len + 1).min(BUFFER_SIZE))
+            })
+            .ok();
+        self.window.write_count.fetch_add(1, Ordering::Release);
+        self.window.dirty.store(true, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Copy a whole block of DMA-filled captures into the ring at once, instead of pushing one
+    /// sample at a time. Rejects the whole block with [`Overrun`] if it wouldn't fit rather than
+    /// partially writing it, same as a single `push` refuses to silently overwrite unread data.
+    pub fn push_many(&mut self, values: &[u32]) -> Result<(), Overrun> {
+        let head = self.window.head.load(Ordering::Relaxed);
+        let tail = self.window.tail.load(Ordering::Acquire);
+
+        if head.wrapping_sub(tail) as usize + values.len() > BUFFER_SIZE {
+            return Err(Overrun);
+        }
+
+        let start = head % BUFFER_SIZE;
+        let first_len = values.len().min(BUFFER_SIZE - start);
+        self.window.buffer[start..start + first_len].copy_from_slice(&values[..first_len]);
+        if first_len < values.len() {
+            let remainder = &values[first_len..];
+            self.window.buffer[..remainder.len()].copy_from_slice(remainder);
+        }
+
+        self.window
+            .head
+            .store(head.wrapping_add(values.len()), Ordering::Release);
+        self.window
+            .len
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |len| {
+                Some((len + values.len()).min(BUFFER_SIZE))
+            })
+            .ok();
+        self.window
+            .write_count
+            .fetch_add(values.len() as u64, Ordering::Release);
+        self.window.dirty.store(true, Ordering::Release);
+
+        Ok(())
+    }
+}
+
+pub struct Consumer<'a, const BUFFER_SIZE: usize> {
+    window: &'a mut SignalWindow<BUFFER_SIZE>,
+}
+
+impl<'a, const BUFFER_SIZE: usize> Consumer<'a, BUFFER_SIZE> {
+    pub fn is_dirty(&self) -> bool {
+        self.window.dirty.load(Ordering::Acquire)
+    }
+
+    /// Mark the current window as read; called once per look, whether or not a pattern matched,
+    /// so the decode task doesn't spin re-processing a window with no new samples.
+    pub fn clear_dirty(&mut self) {
+        self.window.dirty.store(false, Ordering::Release);
+    }
+
+    /// Advance the read position by `count`, to be called once those slots have been consumed
+    /// (e.g. after a pattern match clears them).
+    pub fn advance(&mut self, count: usize) {
+        self.window.tail.fetch_add(count, Ordering::Release);
+    }
+
+    /// Pop the whole valid window into `dst`, oldest first, and advance the read position past
+    /// it in one go. Returns the number of samples written, `dst.len().min(window.len())`. The
+    /// batch counterpart to [`Producer::push_many`] for the same hot-path reason: copying a
+    /// block at once beats popping one sample at a time.
+    pub fn drain_window(&mut self, dst: &mut [u32]) -> usize {
+        let (first, second) = self.window.as_slices();
+        let count = dst.len().min(first.len() + second.len());
+
+        let first_len = count.min(first.len());
+        dst[..first_len].copy_from_slice(&first[..first_len]);
+        let second_len = count - first_len;
+        dst[first_len..first_len + second_len].copy_from_slice(&second[..second_len]);
+
+        self.window
+            .len
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |len| {
+                Some(len.saturating_sub(count))
+            })
+            .ok();
+        self.advance(count);
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_then_get_window_returns_oldest_first() {
+        let mut window: SignalWindow<4> = SignalWindow::new_const();
+        window.push(1);
+        window.push(2);
+        window.push(3);
+
+        let (contents, start) = window.get_window();
+        assert_eq!(start, 0);
+        assert_eq!(&contents[..3], &[1, 2, 3]);
+        assert!(window.is_dirty());
+    }
+
+    #[test]
+    fn push_wraps_and_get_window_starts_at_the_live_region() {
+        let mut window: SignalWindow<4> = SignalWindow::new_const();
+        for value in 1..=6 {
+            window.push(value);
+        }
+
+        // only the last 4 pushes are still live: 3, 4, 5, 6
+        let (contents, start) = window.get_window();
+        assert_eq!(&contents[..4], &[3, 4, 5, 6]);
+        assert_eq!(start, window.region_start());
+    }
+
+    #[test]
+    fn push_many_rejects_a_batch_that_would_overrun() {
+        let mut window: SignalWindow<4> = SignalWindow::new_const();
+        assert!(window.producer().push_many(&[1, 2, 3, 4, 5]).is_err());
+    }
+
+    #[test]
+    fn push_many_matches_as_slices() {
+        let mut window: SignalWindow<4> = SignalWindow::new_const();
+        window.producer().push_many(&[10, 20, 30]).unwrap();
+
+        let (first, second) = window.as_slices();
+        let mut joined = [0u32; 4];
+        let mut i = 0;
+        for &value in first.iter().chain(second.iter()) {
+            joined[i] = value;
+            i += 1;
+        }
+        assert_eq!(&joined[..3], &[10, 20, 30]);
+    }
+
+    #[test]
+    fn get_from_rejects_unwritten_and_overwritten_ranges() {
+        let mut window: SignalWindow<4> = SignalWindow::new_const();
+        for value in 1..=6 {
+            window.push(value);
+        }
+
+        // abs index 1 (value 2) has already been overwritten, only 2..6 are still live
+        assert_eq!(window.get_from(1, 1), None);
+        assert!(window.get_from(2, 4).is_some());
+        // nothing at or beyond write_count has been written yet
+        assert_eq!(window.get_from(6, 1), None);
+    }
+
+    #[test]
+    fn consume_to_clears_up_to_the_requested_absolute_index() {
+        let mut window: SignalWindow<4> = SignalWindow::new_const();
+        window.push(1);
+        window.push(2);
+        window.push(3);
+
+        window.consume_to(2);
+        assert_eq!(window.len(), 1);
+    }
+}
@@ -4,149 +4,252 @@
 #[cfg(not(any(feature = "clock_8_mhz")))]
 compile_error!("A clock frequency must be specified");
 
+mod decoder;
 mod input;
+mod output;
 mod patterns;
 
 use {defmt_rtt as _, panic_probe as _};
 
-use input::{process, InputCapture};
-use patterns::Settings;
-use static_cell::StaticCell;
-use stm32f0xx_hal::{
-    gpio::{self, *},
-    pac::{interrupt, Interrupt, Peripherals, TIM1, TIM14, TIM3},
-    prelude::*,
-    time::Hertz,
-    timers::*,
-};
-
-use core::{cell::RefCell, convert::TryInto, panic};
-use cortex_m::{
-    asm::{self, wfe},
-    interrupt::Mutex,
-    peripheral::Peripherals as c_m_Peripherals,
-};
-use cortex_m_rt::entry;
-
-// A type definition for the GPIO pin to be used for our LED
-type OnboardLedPin = gpioa::PA4<Output<PushPull>>;
-
-// Make LED pin globally available
-static ONBOARD_LED: Mutex<RefCell<Option<OnboardLedPin>>> = Mutex::new(RefCell::new(None));
-
-// Make timer interrupt registers globally available
-static GINT: Mutex<RefCell<Option<Timer<TIM14>>>> = Mutex::new(RefCell::new(None));
-
-// Define an interupt handler, i.e. function to call when interrupt occurs. Here if our external
-// interrupt trips when the timer timed out
-#[interrupt]
-fn TIM14() {
-    static mut LED: Option<OnboardLedPin> = None;
-    static mut INT: Option<Timer<TIM14>> = None;
-
-    let led = LED.get_or_insert_with(|| {
-        cortex_m::interrupt::free(|cs| {
-            // Move LED pin here, leaving a None in its place
-            ONBOARD_LED.borrow(cs).replace(None).unwrap()
-        })
-    });
+#[rtic::app(device = stm32f0xx_hal::pac, dispatchers = [CEC_CAN])]
+mod app {
+    use crate::decoder::Decoder;
+    #[cfg(feature = "dma_capture")]
+    use crate::input::DmaInputCapture;
+    #[cfg(not(feature = "dma_capture"))]
+    use crate::input::{self, InputCapture};
+    use crate::output::OutputPlayback;
+    use crate::patterns::Settings;
+
+    use cortex_m::asm;
+    use rtic::Mutex;
+    use stm32f0xx_hal::{
+        gpio::{self, *},
+        prelude::*,
+        time::Hertz,
+        timers::*,
+    };
+    use systick_monotonic::Systick;
+
+    // 1 kHz SysTick monotonic. Captured periods carry no timestamps from it; the decoder's
+    // inter-frame gap timeout comes from the raw period threshold `GAP_TIMEOUT_TICKS` in `input`.
+    #[monotonic(binds = SysTick, default = true)]
+    type MonoTimer = Systick<1_000>;
+
+    type OnboardLedPin = gpioa::PA4<Output<PushPull>>;
+
+    /// Number of bits a frame is expected to carry before `Decoder` finalizes it.
+    const FRAME_BITS: usize = 16;
+
+    #[shared]
+    struct Shared {
+        settings: Settings,
+        #[cfg(not(feature = "dma_capture"))]
+        input_capture: InputCapture,
+        #[cfg(feature = "dma_capture")]
+        input_capture: DmaInputCapture,
+        decoder: Decoder,
+        output_playback: OutputPlayback,
+    }
 
-    let int = INT.get_or_insert_with(|| {
-        cortex_m::interrupt::free(|cs| {
-            // Move LED pin here, leaving a None in its place
-            GINT.borrow(cs).replace(None).unwrap()
-        })
-    });
+    #[local]
+    struct Local {
+        led: OnboardLedPin,
+        blink_timer: Timer<stm32f0xx_hal::pac::TIM14>,
+    }
 
-    led.toggle().ok();
-    int.wait().ok();
-}
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let mut p = cx.device;
+        let mono = Systick::new(cx.core.SYST, 32_000_000);
 
-#[entry]
-fn main() -> ! {
-    if let Some(cp) = c_m_Peripherals::take() {
-        let mut nvic = cp.NVIC;
+        p.RCC.apb1enr.modify(|_, w| w.tim3en().set_bit());
+        p.RCC.apb2enr.modify(|_, w| w.tim1en().set_bit());
+        p.RCC.ahbenr.modify(|_, w| w.iopaen().set_bit());
+        #[cfg(feature = "dma_capture")]
+        p.RCC.ahbenr.modify(|_, w| w.dmaen().set_bit());
 
-        unsafe {
-            nvic.set_priority(Interrupt::TIM3, 0b1000);
-            nvic.set_priority(Interrupt::TIM14, 0b0001);
-        }
-    } else {
-        panic!("Failed to take core peripherals");
-    }
+        let mut rcc = p
+            .RCC
+            .configure()
+            .sysclk(32.mhz())
+            .pclk(32.mhz())
+            .freeze(&mut p.FLASH);
+
+        let gpioa = p.GPIOA.split(&mut rcc);
+
+        let led = cortex_m::interrupt::free(|cs| gpioa.pa4.into_push_pull_output(cs));
 
-    if let Some(mut p) = Peripherals::take() {
-        cortex_m::interrupt::free(move |cs| {
-            p.RCC.apb1enr.modify(|_, w| w.tim3en().set_bit());
-            p.RCC.ahbenr.modify(|_, w| w.iopaen().set_bit());
-            // p.RCC.apb2enr.modify(|_, w| w.usart1en().set_bit());
-
-            let mut rcc = p
-                .RCC
-                .configure()
-                .sysclk(32.mhz())
-                .pclk(32.mhz())
-                .freeze(&mut p.FLASH);
-
-            let gpioa = p.GPIOA.split(&mut rcc);
-
-            {
-                // (Re-)configure PA4 as output
-                // Move the pin into our global storage
-                let led = gpioa.pa4.into_push_pull_output(cs);
-                *ONBOARD_LED.borrow(cs).borrow_mut() = Some(led);
-            }
-
-            {
-                // Set up a timer expiring after 1s
-                // Generate an interrupt when the timer expires
-                // This is used to test input capture by toggling PA4
-                let mut timer = Timer::tim14(p.TIM14, Hertz(1), &mut rcc);
-                timer.listen(Event::TimeOut);
-                *GINT.borrow(cs).borrow_mut() = Some(timer);
-            }
-
-            {
-                // setup input capturing 434 Mhz
-
-                gpioa.pa6.into_alternate_af1(cs);
-                InputCapture::init(p.TIM3, rcc.clocks.pclk());
-            }
-
-            unsafe {
-                cortex_m::peripheral::NVIC::unmask(Interrupt::TIM14);
-            }
-            cortex_m::peripheral::NVIC::unpend(Interrupt::TIM14);
+        // Set up a timer expiring after 1s, used to test input capture by toggling PA4
+        let mut blink_timer = Timer::tim14(p.TIM14, Hertz(1), &mut rcc);
+        blink_timer.listen(Event::TimeOut);
+
+        // setup input capturing 434 Mhz
+        cortex_m::interrupt::free(|cs| {
+            gpioa.pa6.into_alternate_af1(cs);
         });
-    } else {
-        panic!("Failed to take peripherals");
+
+        #[cfg(not(feature = "dma_capture"))]
+        let mut input_capture = {
+            // RSSI squelch: only accept edges while the receiver reports a real signal on PA0
+            let rssi_pin = cortex_m::interrupt::free(|cs| gpioa.pa0.into_analog(cs));
+            let rssi_adc = stm32f0xx_hal::adc::Adc::new(p.ADC, &mut rcc);
+            const RSSI_THRESHOLD: u16 = 512;
+
+            InputCapture::new(p.TIM3, rcc.clocks.pclk())
+                .with_rssi_gate(rssi_adc, rssi_pin, RSSI_THRESHOLD)
+        };
+
+        #[cfg(feature = "dma_capture")]
+        let input_capture = DmaInputCapture::new(p.TIM3, p.DMA1, rcc.clocks.pclk());
+
+        // Replay side of the learn-and-replay remote: configured and idle as soon as boot, ready
+        // for `OutputPlayback::play` to be called once decoded patterns are available. No task
+        // calls `play` yet — triggering a replay (on a button, a host command, ...) is left to
+        // the caller; see `output` module docs.
+        let output_pin = cortex_m::interrupt::free(|cs| gpioa.pa8.into_alternate_af2(cs));
+        let output_playback = OutputPlayback::new(p.TIM1, output_pin, rcc.clocks.pclk());
+
+        let mut settings = Settings::default();
+
+        let sync_bit = crate::patterns::PeriodPattern::new([360, 11160, 0, 0, 0, 0, 0, 0], 0.15);
+        let high_bit =
+            crate::patterns::PeriodPattern::new([360, 1080, 360, 1080, 0, 0, 0, 0], 0.15);
+        let low_bit =
+            crate::patterns::PeriodPattern::new([360, 1080, 1080, 360, 0, 0, 0, 0], 0.15);
+
+        settings.add_pattern(sync_bit);
+        settings.add_pattern(high_bit);
+        settings.add_pattern(low_bit);
+
+        // wait for a bit
+        asm::delay(4_000_000);
+
+        // with an RSSI gate attached, `tim14_tick` arms capture once the level crosses
+        // RSSI_THRESHOLD instead of enabling it unconditionally here; the DMA capture path has
+        // no RSSI gate and is simply left enabled from `DmaInputCapture::new`.
+        #[cfg(not(feature = "dma_capture"))]
+        input_capture.poll_rssi_gate();
+
+        defmt::info!("Input capture ready");
+
+        (
+            Shared {
+                settings,
+                input_capture,
+                decoder: Decoder::new(FRAME_BITS),
+                output_playback,
+            },
+            Local { led, blink_timer },
+            init::Monotonics(mono),
+        )
     }
 
-    defmt::info!("Hello, world!");
+    #[idle]
+    fn idle(_cx: idle::Context) -> ! {
+        loop {
+            asm::wfi();
+        }
+    }
 
-    static SETTINGS: StaticCell<Settings> = StaticCell::new();
-    let settings = SETTINGS.init(Settings::default());
+    // Blinks the onboard LED so capture can be tested without external hardware, and doubles
+    // as the periodic tick that re-arms/disarms capture via the optional RSSI gate.
+    #[cfg(not(feature = "dma_capture"))]
+    #[task(binds = TIM14, local = [led, blink_timer], shared = [input_capture], priority = 1)]
+    fn tim14_tick(mut cx: tim14_tick::Context) {
+        cx.local.led.toggle().ok();
+        cx.local.blink_timer.wait().ok();
+
+        cx.shared
+            .input_capture
+            .lock(|input_capture| input_capture.poll_rssi_gate());
+    }
 
-    let sync_bit = patterns::PeriodPattern::new([360, 11160, 0, 0, 0, 0, 0, 0], 0.15);
-    let high_bit = patterns::PeriodPattern::new([360, 1080, 360, 1080, 0, 0, 0, 0], 0.15);
-    let low_bit = patterns::PeriodPattern::new([360, 1080, 1080, 360, 0, 0, 0, 0], 0.15);
+    // Blinks the onboard LED so capture can be tested without external hardware. The DMA
+    // capture path has no RSSI gate to poll, so this build has nothing else to do here.
+    #[cfg(feature = "dma_capture")]
+    #[task(binds = TIM14, local = [led, blink_timer], priority = 1)]
+    fn tim14_tick(cx: tim14_tick::Context) {
+        cx.local.led.toggle().ok();
+        cx.local.blink_timer.wait().ok();
+    }
 
-    settings.add_pattern(sync_bit);
-    settings.add_pattern(high_bit);
-    settings.add_pattern(low_bit);
+    // Highest-priority task: read TIM3's capture registers and push the result into the
+    // signal window, then hand decoding off to the lower-priority `process` software task.
+    #[cfg(not(feature = "dma_capture"))]
+    #[task(binds = TIM3, shared = [input_capture], priority = 3)]
+    fn tim3_capture(mut cx: tim3_capture::Context) {
+        let dirty = cx
+            .shared
+            .input_capture
+            .lock(|input_capture| input_capture.handle_interrupt().is_some());
+
+        if dirty {
+            process::spawn().ok();
+        }
+    }
 
-    // Setup communication between interrupt and main thread
+    // DMA capture path: captures are routed straight into DMA1 instead of firing `cc1ie`, so
+    // `TIM3` only ever fires on overflow here; draining/matching is driven by the DMA
+    // half/full-transfer interrupt below instead of every edge.
+    #[cfg(feature = "dma_capture")]
+    #[task(binds = TIM3, shared = [input_capture], priority = 3)]
+    fn tim3_capture(mut cx: tim3_capture::Context) {
+        cx.shared
+            .input_capture
+            .lock(|input_capture| input_capture.on_tim3_interrupt());
+    }
 
-    // wait for a bit
-    asm::delay(4_000_000);
+    // DMA capture path: a half or full transfer of `DMA_CAPTURE_BUFFER` is ready; record which
+    // half and hand draining/matching off to the lower-priority `process` software task, same
+    // division of labor as `tim3_capture` in the interrupt-driven build.
+    #[cfg(feature = "dma_capture")]
+    #[task(binds = DMA1_CH4_5_DMA2_CH3_5, shared = [input_capture], priority = 3)]
+    fn dma_half_ready(mut cx: dma_half_ready::Context) {
+        cx.shared
+            .input_capture
+            .lock(|input_capture| input_capture.on_dma_interrupt());
+
+        process::spawn().ok();
+    }
 
-    InputCapture::enable_input_capture();
+    // Matches the latest signal window against the known patterns. Spawned whenever the
+    // capture ISR pushes a new sample, instead of being polled from a busy superloop.
+    #[cfg(not(feature = "dma_capture"))]
+    #[task(shared = [settings, input_capture, decoder], priority = 2)]
+    fn process(cx: process::Context) {
+        let (mut settings, mut input_capture, mut decoder) =
+            (cx.shared.settings, cx.shared.input_capture, cx.shared.decoder);
+
+        let frame = (&mut settings, &mut input_capture, &mut decoder).lock(
+            |settings, input_capture, decoder| {
+                input::process(settings, input_capture, decoder);
+                decoder.take_frame()
+            },
+        );
+
+        if let Some((bytes, len)) = frame {
+            defmt::info!("Frame ready: {:02x}", &bytes[..len]);
+        }
+    }
 
-    defmt::info!("Input capture enabled");
+    // DMA capture path: draining and pattern matching is self-contained in
+    // `DmaInputCapture::process`, which (like the rest of this path) isn't wired to `Decoder`.
+    #[cfg(feature = "dma_capture")]
+    #[task(shared = [settings, input_capture], priority = 2)]
+    fn process(cx: process::Context) {
+        let (mut settings, mut input_capture) = (cx.shared.settings, cx.shared.input_capture);
 
-    loop {
-        asm::wfi();
+        (&mut settings, &mut input_capture)
+            .lock(|settings, input_capture| input_capture.process(settings));
+    }
 
-        process(settings);
+    // Keeps the OOK output keyed: reloads ARR/CCR1 from the next queued duration on every
+    // update interrupt. Priority 3 so transmit timing doesn't jitter behind decoding.
+    #[task(binds = TIM1_CC, shared = [output_playback], priority = 3)]
+    fn tim1_cc(mut cx: tim1_cc::Context) {
+        cx.shared
+            .output_playback
+            .lock(|output_playback| output_playback.on_interrupt());
     }
 }
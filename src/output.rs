@@ -0,0 +1,124 @@
+//! Transmit / replay subsystem.
+//!
+//! Keys a GPIO OOK output by driving a timer in output-compare toggle mode: each entry in a
+//! `PeriodPattern`'s `periods` array becomes one ARR reload, so the pin toggles at exactly the
+//! microsecond intervals that were learned from a captured remote. Reuses the same `res_micro`
+//! 1 MHz timebase as `InputCapture` for symmetry between record and replay, turning the device
+//! into a learn-and-replay RF remote.
+//!
+//! `OutputPlayback` is a plain RTIC resource (no internal singleton), constructed in `app::init`
+//! and driven by the `TIM1_CC` RTIC task; deciding *when* to call [`OutputPlayback::play`] (on a
+//! button press, a host command, ...) is left to the caller, since this crate doesn't yet have
+//! such a trigger wired up.
+
+use core::convert::TryInto;
+
+use stm32f0xx_hal::{
+    gpio::{gpioa::PA8, Alternate, AF2},
+    pac::{Interrupt, TIM1},
+    time::Hertz,
+};
+
+use crate::patterns::PeriodPattern;
+
+/// TIM1_CH1, used to key the OOK output.
+type OutputPin = PA8<Alternate<AF2>>;
+
+/// Durations (in timer ticks) still queued for transmission. One entry per `PeriodPattern`
+/// period, flattened across the whole sequence passed to `play`. A future improvement is to
+/// feed this from DMA instead of reloading ARR/CCR from the update interrupt, so long frames
+/// transmit without CPU babysitting.
+const PLAYBACK_QUEUE_SIZE: usize = 256;
+
+pub struct OutputPlayback {
+    tim1: TIM1,
+    _pin: OutputPin,
+    queue: heapless::Deque<u16, PLAYBACK_QUEUE_SIZE>,
+}
+
+impl OutputPlayback {
+    /// Configure TIM1 CH1 for output-compare toggle mode on `pin`, idle (not transmitting)
+    /// until [`OutputPlayback::play`] is called.
+    pub fn new(tim1: TIM1, pin: OutputPin, pclk: Hertz) -> Self {
+        defmt::assert!(tim1.cr1.read().cen().is_disabled());
+
+        // same 1 MHz timebase as InputCapture, so durations are interchangeable between the two
+        #[cfg(feature = "res_micro")]
+        let target_timer_frequ_hz = Hertz(1_000_000);
+
+        let psc = (pclk.0 / target_timer_frequ_hz.0) - 1;
+        if psc > 0xFFFF {
+            panic!("PSC value too large at {}", psc);
+        }
+        let psc: u16 = psc.try_into().unwrap();
+        tim1.psc.modify(|_, w| w.psc().bits(psc));
+
+        // CH1 in toggle mode: the output pin flips every time the counter hits CCR1
+        tim1.ccmr1_output()
+            .modify(|_, w| w.oc1m().bits(0b011).oc1pe().clear_bit());
+        tim1.ccer.modify(|_, w| w.cc1e().set_bit());
+
+        // main-output-enable, required on advanced-control timers like TIM1
+        tim1.bdtr.modify(|_, w| w.moe().set_bit());
+
+        tim1.dier.modify(|_, w| w.uie().set_bit());
+
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(Interrupt::TIM1_CC);
+        }
+        cortex_m::peripheral::NVIC::unpend(Interrupt::TIM1_CC);
+
+        defmt::info!("Output playback setup done");
+
+        Self {
+            tim1,
+            _pin: pin,
+            queue: heapless::Deque::new(),
+        }
+    }
+
+    /// Flatten an ordered sequence of patterns (sync + the high/low bitstream) into a queue of
+    /// durations and start keying the output. Returns once the whole sequence has been queued;
+    /// transmission itself finishes asynchronously, one period per update interrupt.
+    pub fn play(&mut self, patterns: &[PeriodPattern<8>]) {
+        defmt::assert!(self.tim1.cr1.read().cen().is_disabled());
+        self.queue.clear();
+
+        for pattern in patterns {
+            for period in pattern {
+                if self.queue.push_back(period).is_err() {
+                    defmt::error!("Playback queue full, truncating pattern sequence");
+                    break;
+                }
+            }
+        }
+
+        self.load_next();
+        self.tim1.cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    /// Reload ARR/CCR1 from the next queued duration, disabling the timer once the queue runs
+    /// dry. The output pin then keeps whatever level the last toggle left it at.
+    fn load_next(&mut self) {
+        match self.queue.pop_front() {
+            Some(duration) => {
+                self.tim1.arr.write(|w| w.arr().bits(duration));
+                self.tim1.ccr1.write(|w| w.ccr().bits(duration));
+                self.tim1.egr.write(|w| w.ug().set_bit());
+            }
+            None => {
+                self.tim1.cr1.modify(|_, w| w.cen().clear_bit());
+                defmt::info!("Playback finished");
+            }
+        }
+    }
+
+    /// Handle the `TIM1_CC` interrupt: bound to the RTIC `TIM1_CC` task in place of a bare
+    /// `#[interrupt]` handler.
+    pub fn on_interrupt(&mut self) {
+        if self.tim1.sr.read().uif().bit_is_set() {
+            self.tim1.sr.modify(|_, w| w.uif().clear_bit());
+            self.load_next();
+        }
+    }
+}
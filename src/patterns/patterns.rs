@@ -1,8 +1,24 @@
+/// Pack a `(mark, space)` pair captured by dual-channel PWM-input into a single `u32` sample,
+/// so it can travel through the same `SignalWindow<u32>` as raw inter-edge periods.
+#[inline]
+pub fn pack_mark_space(mark: u16, space: u16) -> u32 {
+    ((mark as u32) << 16) | (space as u32)
+}
+
+/// The inverse of [`pack_mark_space`].
+#[inline]
+pub fn unpack_mark_space(value: u32) -> (u16, u16) {
+    ((value >> 16) as u16, value as u16)
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct PeriodPattern<const PERIOD_SIZE: usize> {
     pub periods: [u16; PERIOD_SIZE],
     pub size: u8,
     pub tolerance: f64,
+    /// When set, `periods` holds alternating `mark, space` expectations and `match_window`
+    /// compares them against packed `(mark, space)` samples instead of raw periods.
+    pub mark_space: bool,
 }
 
 impl<const PERIOD_SIZE: usize> Default for PeriodPattern<PERIOD_SIZE> {
@@ -19,6 +35,17 @@ impl<const PERIOD_SIZE: usize> PeriodPattern<PERIOD_SIZE> {
             periods,
             size: size,
             tolerance: tolerance,
+            mark_space: false,
+        }
+    }
+
+    /// Like [`PeriodPattern::new`], but matches against packed `(mark, space)` samples (see
+    /// [`pack_mark_space`]) instead of raw inter-edge periods. `periods` alternates expected
+    /// `mark, space, mark, space, ...` values.
+    pub fn new_mark_space(periods: [u16; PERIOD_SIZE], tolerance: f64) -> Self {
+        Self {
+            mark_space: true,
+            ..Self::new(periods, tolerance)
         }
     }
 
@@ -27,15 +54,35 @@ impl<const PERIOD_SIZE: usize> PeriodPattern<PERIOD_SIZE> {
             periods: [0; PERIOD_SIZE],
             size: 0,
             tolerance: 0.0,
+            mark_space: false,
         }
     }
 
+    #[inline]
+    fn in_tolerance(&self, target: u16, signal: u16) -> Option<bool> {
+        if target == 0 {
+            return None; // signals 'done' to the caller
+        }
+
+        let target_val = f64::from(target);
+        let signal_val = f64::from(signal);
+
+        Some(
+            target_val * (1.0 - self.tolerance) < signal_val
+                && signal_val < target_val * (1.0 + self.tolerance),
+        )
+    }
+
     #[inline]
     pub fn match_window(&self, signal_pattern: &[u32; PERIOD_SIZE]) -> bool {
         if self.size == 0 {
             return false;
         }
 
+        if self.mark_space {
+            return self.match_window_mark_space(signal_pattern);
+        }
+
         for signal_index in 0..PERIOD_SIZE {
             let target_val = f64::from(self.periods[signal_index]);
             let signal_period = f64::from(signal_pattern[signal_index]);
@@ -68,6 +115,29 @@ impl<const PERIOD_SIZE: usize> PeriodPattern<PERIOD_SIZE> {
 
         true
     }
+
+    /// `match_window` for patterns built with [`PeriodPattern::new_mark_space`]: each window
+    /// sample is a packed `(mark, space)` pair, compared against two consecutive `periods`
+    /// entries instead of one raw period.
+    fn match_window_mark_space(&self, signal_pattern: &[u32; PERIOD_SIZE]) -> bool {
+        for pair_index in 0..(PERIOD_SIZE / 2) {
+            let (mark, space) = unpack_mark_space(signal_pattern[pair_index]);
+
+            match self.in_tolerance(self.periods[pair_index * 2], mark) {
+                None => return true, // we are 'done'
+                Some(false) => return false,
+                Some(true) => {}
+            }
+
+            match self.in_tolerance(self.periods[pair_index * 2 + 1], space) {
+                None => return true,
+                Some(false) => return false,
+                Some(true) => {}
+            }
+        }
+
+        true
+    }
 }
 
 // create a read only interator